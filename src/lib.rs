@@ -8,8 +8,27 @@ extern crate syntax;
 pub mod sorty;
 
 use rustc::plugin::Registry;
+use sorty::SortConfig;
+use syntax::ast::NestedMetaItemKind;
 
 #[plugin_registrar]
 pub fn plugin_registrar(reg: &mut Registry) {
-    reg.register_early_lint_pass(box sorty::Sorty);
+    // `#![plugin(sorty(merge_nested_uses, case_insensitive, grouped_imports))]` toggles the
+    // opt-in behaviors; unset args fall back to the original hard-coded ordering
+    let has_arg = |name: &str| {
+        reg.args().iter().any(|arg| {
+            match arg.node {
+                NestedMetaItemKind::MetaItem(ref meta) => meta.name.as_str() == name,
+                NestedMetaItemKind::Literal(_) => false,
+            }
+        })
+    };
+
+    let merge_nested_uses = has_arg("merge_nested_uses");
+    let sort_config = SortConfig {
+        case_insensitive: has_arg("case_insensitive"),
+        grouped_imports: has_arg("grouped_imports"),
+    };
+
+    reg.register_early_lint_pass(box sorty::Sorty::new(merge_nested_uses, sort_config));
 }
@@ -1,16 +1,53 @@
 use rustc::lint::{EarlyContext, EarlyLintPass, LintArray, LintContext, LintPass};
 use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet};
 use syntax::ast::{Item, ItemKind, LitKind, MetaItemKind, Mod, NodeId};
 use syntax::ast::{NestedMetaItemKind, ViewPath_, Visibility};
-use syntax::codemap::Span;
+use syntax::codemap::{BytePos, CodeMap, Span};
+use syntax::errors::Applicability;
 use syntax::print::pprust::path_to_string;
+use syntax::ptr::P;
 use syntax::symbol::keywords;
 
 // Warn unsorted declarations by default (since denying is a poor choice for styling lints)
 declare_lint!(UNSORTED_DECLARATIONS, Warn,
               "Warn when the declarations of crates or modules are not in alphabetical order");
 
-pub struct Sorty;
+pub struct Sorty {
+    // Beyond sorting, also consolidate `use` items that share a path prefix into one nested
+    // import. Opt-in, since it rewrites import lists rather than just reordering them.
+    merge_nested_uses: bool,
+    // the comparator and (for `use` statements) grouping policy used by `check_sort`
+    sort_config: SortConfig,
+}
+
+impl Default for Sorty {
+    fn default() -> Sorty {
+        Sorty { merge_nested_uses: false, sort_config: SortConfig::default() }
+    }
+}
+
+impl Sorty {
+    pub fn new(merge_nested_uses: bool, sort_config: SortConfig) -> Sorty {
+        Sorty { merge_nested_uses: merge_nested_uses, sort_config: sort_config }
+    }
+}
+
+/// Picks how declarations are ordered: the comparison key, and (for `use` statements only)
+/// whether they're additionally bucketed into std/extern-crate/local groups separated by a
+/// blank line in the suggestion. The existing `macro_use`-first and `pub`-last biasing always
+/// applies on top of this, so the default config reproduces the old hard-coded behavior.
+#[derive(Clone, Copy)]
+pub struct SortConfig {
+    pub case_insensitive: bool,
+    pub grouped_imports: bool,
+}
+
+impl Default for SortConfig {
+    fn default() -> SortConfig {
+        SortConfig { case_insensitive: false, grouped_imports: false }
+    }
+}
 
 impl LintPass for Sorty {
     fn get_lints(&self) -> LintArray {
@@ -18,20 +55,98 @@ impl LintPass for Sorty {
     }
 }
 
-impl EarlyLintPass for Sorty {
-    // Walking through all the modules is enough for our purpose
-    fn check_mod(&mut self, cx: &EarlyContext, module: &Mod, _span: Span, _id: NodeId) {
-        // TODO: lint should stop ignoring the comments near the declarations
+/// A node in the prefix trie used to merge `use` paths that share a common prefix. Segments
+/// are the module path leading up to this node (e.g. `io` under `std`); `leaves` are the names
+/// hanging directly off it (a plain import, `self`, `*` for a glob, or `name as alias`).
+#[derive(Default)]
+struct UseTrie {
+    children: BTreeMap<String, UseTrie>,
+    leaves: BTreeSet<String>,
+}
+
+impl UseTrie {
+    fn insert(&mut self, path: &[String], leaf: String) {
+        match path.split_first() {
+            Some((head, rest)) => {
+                self.children.entry(head.clone()).or_insert_with(UseTrie::default).insert(rest, leaf);
+            }
+            None => { self.leaves.insert(leaf); }
+        }
+    }
+
+    // Serializes everything below this node into a sorted list of path fragments, collapsing
+    // single-child chains into `a::b` and branching into `{...}` wherever a node has more than
+    // one child or leaf.
+    fn fragments(&self) -> Vec<String> {
+        let mut entries = self.leaves.iter().map(|leaf| (leaf.clone(), leaf.clone())).collect::<Vec<_>>();
+
+        for (segment, child) in &self.children {
+            let inner = child.fragments();
+            let fragment = match inner.len() {
+                1 => format!("{}::{}", segment, inner[0]),
+                _ => format!("{}::{{{}}}", segment, inner.join(", ")),
+            };
+            entries.push((segment.clone(), fragment));
+        }
+
+        entries.sort_by(|a, b| match (&*a.0, &*b.0) {     // `self` goes first, as elsewhere
+            ("self", _) => Ordering::Less,
+            (_, "self") => Ordering::Greater,
+            _ => a.0.cmp(&b.0),
+        });
+        entries.into_iter().map(|(_, fragment)| fragment).collect()
+    }
+
+    // Serializes the root: each top-level child becomes its own merged `use` path, since
+    // unrelated crates/roots have no prefix worth sharing.
+    fn serialize(&self) -> Vec<String> {
+        let mut paths = self.leaves.iter().cloned().collect::<Vec<_>>();
+        for (segment, child) in &self.children {
+            let inner = child.fragments();
+            paths.push(match inner.len() {
+                1 => format!("{}::{}", segment, inner[0]),
+                _ => format!("{}::{{{}}}", segment, inner.join(", ")),
+            });
+        }
+        paths.sort();
+        paths
+    }
+}
+
+impl Sorty {
+    // Collects and order-checks the declarations in `items`. Inline module bodies are not
+    // recursed into here: the lint driver calls `check_mod` on them separately as it walks
+    // the crate, which runs this same function over their contents on its own.
+    fn check_items(&mut self, cx: &EarlyContext, items: &[P<Item>]) {
         let session_codemap = cx.sess.codemap();    // required only for checking inline mods
         let mut extern_crates = Vec::new();
         let mut uses = Vec::new();
         let mut mods = Vec::new();
+        // only populated when `merge_nested_uses` is on: (path segments, leaf, attrs, span, run)
+        let mut merge_use_paths = Vec::new();
+        // the end of the previous item's span, used to grab the source text (and thus any
+        // attached comments) lying between it and the declaration we're currently looking at
+        let mut prev_item_hi = None;
+        // tracks the contiguous run of mergeable `use` statements currently being collected, so
+        // `check_merge` never bounds a replacement span across an item that interrupts it (see
+        // `run_id_for`)
+        let mut current_run: Option<(String, u32)> = None;
+        let mut next_run_id = 0u32;
 
-        for item in &module.items {
+        for item in items {
             // I've made use of `format!` most of the time, because we have a mixture of
             // `String` & `InternedString`
             let item_name = format!("{}", item.ident.name.as_str());
             let item_span = item.span;
+            let (item_comment, item_comment_len) = match prev_item_hi {
+                Some(prev_hi) => leading_comment(session_codemap, prev_hi, item_span),
+                None => (String::new(), 0),
+            };
+            prev_item_hi = Some(item_span.hi);
+            // set by every `merge_use_paths` push below; if this item didn't contribute one, it
+            // breaks the current run, whether it's not a `use` at all or just wasn't merged
+            let mut merged_this_item = false;
+
             match item.node {
                 ItemKind::ExternCrate(ref optional_name) if item_name != "std" => {
                     // We've put the declaration here because, we have to sort crate declarations
@@ -44,16 +159,20 @@ impl EarlyLintPass for Sorty {
                         None => format!("{}extern crate", item_attrs),
                     };
 
-                    extern_crates.push((item_name, item_attrs, item_span, false));
+                    extern_crates.push((item_name, item_attrs, item_comment, item_comment_len, item_span, false));
                 }
 
                 ItemKind::Mod(ref module) => {
                     let mod_invoked_file = session_codemap.span_to_filename(item.span);
                     let mod_declared_file = session_codemap.span_to_filename(module.inner);
-                    if mod_declared_file != mod_invoked_file {          // ignores inline modules
+                    if mod_declared_file != mod_invoked_file {
                         let item_attrs = get_item_attrs(&item, true);
-                        mods.push((item_name, item_attrs, item_span, false));
+                        mods.push((item_name, item_attrs, item_comment, item_comment_len, item_span, false));
                     }
+                    // inline modules have no `mod foo;` declaration of their own to sort here,
+                    // but `check_mod` is already invoked by the lint driver for every module it
+                    // walks, inline or not, so their bodies get their own pass without us
+                    // recursing manually (which would otherwise double up every warning inside)
                 }
 
                 ItemKind::Use(ref spanned) => {
@@ -71,7 +190,21 @@ impl EarlyLintPass for Sorty {
                                 }
                             };
 
-                            uses.push((renamed, item_attrs, item_span, false));
+                            if self.merge_nested_uses {
+                                let mut segments = path_str.split("::")
+                                                            .map(|s| s.to_owned())
+                                                            .collect::<Vec<_>>();
+                                let last = segments.pop().unwrap_or_default();
+                                let leaf = match last == &*name {
+                                    true => last,
+                                    false => format!("{} as {}", last, name),
+                                };
+                                let run = run_id_for(&mut current_run, &mut next_run_id, &item_attrs);
+                                merge_use_paths.push((segments, leaf, item_attrs, item_span, run));
+                                merged_this_item = true;
+                            } else {
+                                uses.push((renamed, item_attrs, item_comment, item_comment_len, item_span, false));
+                            }
                         }
 
                         ViewPath_::ViewPathList(ref path, ref list) => {
@@ -87,6 +220,30 @@ impl EarlyLintPass for Sorty {
                                 }
                             }).collect::<Vec<_>>();
 
+                            if self.merge_nested_uses {
+                                let segments = path_to_string(&path).split("::")
+                                                                     .map(|s| s.to_owned())
+                                                                     .collect::<Vec<_>>();
+                                let run = run_id_for(&mut current_run, &mut next_run_id, &item_attrs);
+                                for leaf in old_list {
+                                    merge_use_paths.push((segments.clone(), leaf,
+                                                          item_attrs.clone(), item_span, run));
+                                }
+                                merged_this_item = true;
+                                continue
+                            }
+
+                            // a one-element list (other than `{self}`) has unnecessary braces:
+                            // `use foo::{bar};` should just be `use foo::bar;`. This rewrites
+                            // the whole statement (braces and all), not just the path prefix, so
+                            // it needs `item_span` — using `path.span` here would only replace
+                            // the `foo` token, turning the fix into `use use foo::bar;::{bar};`
+                            if old_list.len() == 1 && old_list[0] != "self" {
+                                let use_list = format!("{}::{}", path_to_string(&path), old_list[0]);
+                                uses.push((use_list, item_attrs, item_comment, item_comment_len, item_span, true));
+                                continue
+                            }
+
                             let mut new_list = old_list.clone();
                             new_list.sort_by(|a, b| {
                                 match (&**a, &**b) {    // `self` should be first in a list of use items
@@ -106,7 +263,12 @@ impl EarlyLintPass for Sorty {
                                 }
                             }
 
-                            uses.push((use_list, item_attrs, path.span, warn));
+                            // `path.span` covers only the path prefix (e.g. `foo` in
+                            // `foo::{bar, baz}`), not the `use ` keyword or the `::{...};`
+                            // suffix, unlike every other `use` kind's stored span. Store the
+                            // full statement span instead, or an auto-applied suggestion based
+                            // on it leaves the keyword/suffix behind (or duplicated).
+                            uses.push((use_list, item_attrs, item_comment, item_comment_len, item_span, warn));
                         }
 
                         ViewPath_::ViewPathGlob(ref path) => {
@@ -114,29 +276,155 @@ impl EarlyLintPass for Sorty {
                             // We don't have any use statements like `use std::prelude::*`
                             // since it's done only by rustc, we can safely neglect those here
                             if !path_str.starts_with("std::") {
-                                uses.push((path_str, item_attrs, item_span, false));
+                                if self.merge_nested_uses {
+                                    let segments = path_to_string(&path).split("::")
+                                                                         .map(|s| s.to_owned())
+                                                                         .collect::<Vec<_>>();
+                                    let run = run_id_for(&mut current_run, &mut next_run_id, &item_attrs);
+                                    merge_use_paths.push((segments, "*".to_owned(),
+                                                          item_attrs, item_span, run));
+                                    merged_this_item = true;
+                                } else {
+                                    uses.push((path_str, item_attrs, item_comment, item_comment_len, item_span, false));
+                                }
                             }
                         }
                     }
                 }
                 _ => (),
             }
+
+            if !merged_this_item {
+                current_run = None;
+            }
+        }
+
+        if self.merge_nested_uses {
+            check_merge(cx, session_codemap, self.sort_config, merge_use_paths);
         }
 
         // We don't include the crate declaration here, because we've already appended it with the
         // attributes
-        check_sort(&extern_crates, cx, "crate declarations", "");
-        check_sort(&mods, cx, "module declarations (other than inline modules)", "mod");
-        check_sort(&uses, cx, "use statements", "use");
+        check_sort(&extern_crates, cx, session_codemap, self.sort_config, "crate declarations", "");
+        check_sort(&mods, cx, session_codemap, self.sort_config,
+                   "module declarations (other than inline modules)", "mod");
+        check_sort(&uses, cx, session_codemap, self.sort_config, "use statements", "use");
+
+        // Groups same-prefix `use` paths (collected while `merge_nested_uses` is enabled) into
+        // nested imports, e.g. `std::io::Read` + `std::io::Write` -> `std::io::{Read, Write}`,
+        // and warns whenever that merged-and-sorted form doesn't already match the source
+        // (whitespace aside). We can't route this through `check_sort`: merging can collapse N original
+        // statements into M <= N merged ones, and `check_sort`'s pairwise comparison assumes
+        // the same items just change order, never cardinality. So instead, diff the rendered
+        // suggestion directly against the literal text it would replace. Items whose attributes
+        // differ are kept in separate groups (and get their own diagnostic), so they never get
+        // merged into the same `use` statement -- and so are items that happen to share attrs
+        // but aren't part of the same contiguous run of mergeable `use` statements (tagged with
+        // the same run id by `check_items`), since a single bounding span across the two would
+        // swallow (or corrupt) whatever sits between them.
+        fn check_merge(cx: &EarlyContext, codemap: &CodeMap, config: SortConfig,
+                        items: Vec<(Vec<String>, String, String, Span, u32)>) {
+            let mut by_run: Vec<(String, UseTrie, Span, u32)> = Vec::new();
+
+            for (segments, leaf, attrs, span, run) in items {
+                let group = match by_run.iter().position(|&(ref a, _, _, r)| *a == attrs && r == run) {
+                    Some(i) => i,
+                    None => {
+                        by_run.push((attrs, UseTrie::default(), span, run));
+                        by_run.len() - 1
+                    }
+                };
+                let &mut (_, ref mut trie, ref mut full_span, _) = &mut by_run[group];
+                if span.lo < full_span.lo {
+                    full_span.lo = span.lo;
+                }
+                if span.hi > full_span.hi {
+                    full_span.hi = span.hi;
+                }
+                trie.insert(&segments, leaf);
+            }
+
+            for (attrs, trie, mut full_span, _run) in by_run {
+                // `full_span.lo` still sits at the first contributing item's own keyword, after
+                // its line's leading whitespace; pull it back to cover that too, same as
+                // `check_sort` does, or the suggestion's own copy of the indentation would
+                // double up with what's left behind in the source.
+                let indent = leading_indent(codemap, full_span);
+                full_span.lo = BytePos(full_span.lo.0 - indent.len() as u32);
+
+                let mut merged = trie.serialize();
+                merged.sort_by(|a, b| {
+                    let (key_a, key_b) = match config.case_insensitive {
+                        true => (a.to_lowercase(), b.to_lowercase()),
+                        false => (a.clone(), b.clone()),
+                    };
+                    match config.grouped_imports {
+                        true => use_group(a).cmp(&use_group(b)).then_with(|| key_a.cmp(&key_b)),
+                        false => key_a.cmp(&key_b),
+                    }
+                });
+
+                let mut suggestion_list = Vec::new();
+                let mut prev_group = None;
+                for name in &merged {
+                    if config.grouped_imports {
+                        let group = use_group(name);
+                        if prev_group.map_or(false, |prev| prev != group) {
+                            suggestion_list.push(String::new());
+                        }
+                        prev_group = Some(group);
+                    }
+                    suggestion_list.push(format!("{}{}use {};", indent, attrs, name));
+                }
+                let suggestion = suggestion_list.join("\n");
+
+                // Compare with whitespace collapsed, since the source spread across several
+                // lines/statements will never be byte-identical to the merged one-liner(s) we
+                // build above even when the *content* already matches (nothing to merge, and
+                // already sorted).
+                let current = codemap.span_to_snippet(full_span).unwrap_or_default();
+                let normalize = |s: &str| s.split_whitespace().collect::<Vec<_>>().join(" ");
+                if normalize(&current) == normalize(&suggestion) {
+                    continue
+                }
+
+                let message = "use statements sharing a path prefix can be merged, and should \
+                                be in alphabetical order!";
+                cx.struct_span_lint(UNSORTED_DECLARATIONS, full_span, message)
+                  .span_suggestion_with_applicability(full_span, "merge and sort them like this",
+                                                        suggestion, Applicability::MachineApplicable)
+                  .emit();
+            }
+        }
+
+        // Hands back the id of the run a mergeable `use` item's attrs belong to, extending the
+        // current run if the attrs match it or starting a new one otherwise. Callers reset
+        // `current_run` to `None` themselves whenever an item isn't merged, so an interleaving
+        // item (or a run of identical attrs separated by one) always starts a fresh id instead
+        // of being folded into the run before it.
+        fn run_id_for(current_run: &mut Option<(String, u32)>, next_run_id: &mut u32, attrs: &str) -> u32 {
+            if let Some((ref run_attrs, id)) = *current_run {
+                if run_attrs == attrs {
+                    return id
+                }
+            }
+            let id = *next_run_id;
+            *next_run_id += 1;
+            *current_run = Some((attrs.to_owned(), id));
+            id
+        }
 
         // for collecting, formatting & filtering the attributes (and checking the visibility)
         fn get_item_attrs(item: &Item, pub_check: bool) -> String {
             let mut attr_vec = item.attrs.iter().filter_map(|attr| {
                 attr.meta().and_then(|meta| {
-                    let meta_string = get_meta_as_string(&meta.name.as_str(), &meta.node);
-                    match meta_string.starts_with("doc = ") {
-                        true => None,
-                        false => Some(format!("#[{}]", meta_string)),
+                    // `///` (and `//!`) doc comments desugar to `#[doc = "..."]` attributes;
+                    // emit them back as doc comment lines instead of dropping them, so a
+                    // reordered item keeps its documentation rather than losing it silently
+                    match (&*meta.name.as_str(), &meta.node) {
+                        ("doc", &MetaItemKind::NameValue(ref literal)) =>
+                            Some(format!("///{}", format_literal(&literal.node))),
+                        _ => Some(format!("#[{}]", get_meta_as_string(&meta.name.as_str(), &meta.node))),
                     }
                 })
             }).collect::<Vec<_>>();
@@ -145,7 +433,11 @@ impl EarlyLintPass for Sorty {
                 match (&**a, &**b) {    // put `macro_use` first for later checking
                     ("#[macro_use]", _) => Ordering::Less,
                     (_, "#[macro_use]") => Ordering::Greater,
-                    _ => a.cmp(b),
+                    _ => match (a.starts_with("///"), b.starts_with("///")) {
+                        (true, false) => Ordering::Less,   // then doc comments, ahead of other attrs
+                        (false, true) => Ordering::Greater,
+                        _ => a.cmp(b),
+                    },
                 }
             });
 
@@ -192,61 +484,196 @@ impl EarlyLintPass for Sorty {
             }
         }
 
-        // Checks the sorting of all the declarations and raises warnings whenever necessary
-        // takes a slice of tuples with name, related attributes, spans and whether to warn for
-        // unordered use lists
-        fn check_sort(old_list: &[(String, String, Span, bool)], cx: &EarlyContext,
-                      kind: &str, syntax: &str) {
-
-            // prepend given characters to the names for "biased" sorting
-            fn str_for_biased_sort(string: &str, choice: bool, prepend_char: &str) -> String {
-                match choice {
-                    true => prepend_char.to_owned() + string,
-                    false => string.to_owned(),
+        // Grabs the whitespace a declaration's span is indented with, so that a rewritten
+        // block lines up with the rest of the module instead of being jammed to column 0.
+        fn leading_indent(codemap: &CodeMap, span: Span) -> String {
+            let loc = codemap.lookup_char_pos(span.lo);
+            let line = loc.file.get_line(loc.line - 1).map(|line| line.into_owned()).unwrap_or_default();
+            let indent_len = line.len() - line.trim_left().len();
+            line[..indent_len].to_owned()
+        }
+
+        // Grabs any `//`/`/* */` comment lines immediately attached to a declaration, reading
+        // backward from the gap between it and the previous item. A blank line marks the end
+        // of the attached block (and isn't itself carried along), so trailing comments on the
+        // previous item are left where they are. Returns the reconstructed comment text (used
+        // in the suggestion) alongside the number of bytes it actually spans in the source --
+        // trailing whitespace trimmed off each line, and CRLF line endings, mean that can differ
+        // from the reconstructed text's own length, and `check_sort` needs the real source byte
+        // count to size the span it replaces.
+        fn leading_comment(codemap: &CodeMap, prev_hi: BytePos, item_span: Span) -> (String, u32) {
+            let mut gap_span = item_span;
+            gap_span.lo = prev_hi;
+            gap_span.hi = item_span.lo;
+
+            let snippet = codemap.span_to_snippet(gap_span).unwrap_or_default();
+
+            // split on '\n', pairing each line's text (terminator stripped) with the exact
+            // number of source bytes it occupies including that terminator, so the byte count
+            // below reflects the source rather than whatever `trim_right` leaves of the text
+            let mut line_spans = Vec::new();
+            let mut start = 0;
+            for (i, _) in snippet.match_indices('\n') {
+                line_spans.push((&snippet[start..i], i + 1 - start));
+                start = i + 1;
+            }
+            line_spans.push((&snippet[start..], snippet.len() - start));
+
+            // the last line is just the current item's own leading indentation, up to (but not
+            // including) its keyword, never part of an attached comment — drop it before scanning
+            line_spans.pop();
+
+            let mut comment_lines = Vec::new();
+            let mut comment_bytes = 0u32;
+            while let Some((line, byte_len)) = line_spans.pop() {
+                let trimmed = line.trim();
+                if trimmed.is_empty() {
+                    break
+                } else if trimmed.starts_with("//") || trimmed.starts_with("/*") || trimmed.starts_with('*') {
+                    comment_lines.push(line.trim_right().to_owned());
+                    comment_bytes += byte_len as u32;
+                } else {
+                    break
                 }
             }
+            comment_lines.reverse();
+
+            match comment_lines.is_empty() {
+                true => (String::new(), 0),
+                false => (comment_lines.join("\n") + "\n", comment_bytes),
+            }
+        }
+
+        // Classifies a `use` path's root into the group `grouped_imports` sorts it under:
+        // standard library first, then external crates, then local (`crate`/`self`/`super`)
+        // paths last.
+        fn use_group(name: &str) -> u8 {
+            match name.splitn(2, "::").next().unwrap_or(name) {
+                "std" | "core" | "alloc" => 0,
+                "crate" | "self" | "super" => 2,
+                _ => 1,
+            }
+        }
+
+        // prepend given characters to the names for "biased" sorting
+        fn str_for_biased_sort(string: &str, choice: bool, prepend_char: &str) -> String {
+            match choice {
+                true => prepend_char.to_owned() + string,
+                false => string.to_owned(),
+            }
+        }
+
+        // Compares two declarations under `config`'s sort key, keeping the existing
+        // `macro_use`-first and `pub`-last biasing on top regardless of config.
+        fn compare_entries(a: &(String, String, String, bool), b: &(String, String, String, bool),
+                           config: &SortConfig) -> Ordering {
+            let &(ref str_a, ref attr_a, _, _) = a;
+            let &(ref str_b, ref attr_b, _, _) = b;
+
+            // case-insensitive mode compares lowercased names, falling back to the original
+            // to break ties deterministically (so `Foo` and `foo` don't compare equal)
+            let (key_a, tie_a) = match config.case_insensitive {
+                true => (str_a.to_lowercase(), str_a.clone()),
+                false => (str_a.clone(), String::new()),
+            };
+            let (key_b, tie_b) = match config.case_insensitive {
+                true => (str_b.to_lowercase(), str_b.clone()),
+                false => (str_b.clone(), String::new()),
+            };
+
+            // move the `pub` statements below
+            // (with `~` since it's on the farther side of ASCII)
+            let mut new_key_a = str_for_biased_sort(&key_a, attr_a.ends_with("pub "), "~");
+            let mut new_key_b = str_for_biased_sort(&key_b, attr_b.ends_with("pub "), "~");
+            // move the #[macro_use] stuff above
+            // (with `!` since it's on the lower extreme of ASCII)
+            new_key_a = str_for_biased_sort(&new_key_a, attr_a.starts_with("#[macro_use]"), "!");
+            new_key_b = str_for_biased_sort(&new_key_b, attr_b.starts_with("#[macro_use]"), "!");
+
+            (new_key_a, tie_a).cmp(&(new_key_b, tie_b))
+        }
+
+        // Checks the sorting of all the declarations and raises warnings whenever necessary
+        // takes a slice of tuples with name, related attributes, attached comment (plus its
+        // real source byte length), span and whether to warn for unordered use lists
+        fn check_sort(old_list: &[(String, String, String, u32, Span, bool)], cx: &EarlyContext,
+                      codemap: &CodeMap, config: SortConfig, kind: &str, syntax: &str) {
 
-            let mut new_list = old_list.iter().map(|&(ref name, ref attrs, _span, warn)| {
-               (name.clone(), attrs.clone(), warn)
+            let grouped = config.grouped_imports && syntax == "use";
+
+            let mut new_list = old_list.iter().map(|&(ref name, ref attrs, ref comment, _comment_len, _span, warn)| {
+               (name.clone(), attrs.clone(), comment.clone(), warn)
             }).collect::<Vec<_>>();
 
-            new_list.sort_by(|&(ref str_a, ref attr_a, _), &(ref str_b, ref attr_b, _)| {
-                // move the `pub` statements below
-                // (with `~` since it's on the farther side of ASCII)
-                let mut new_str_a = str_for_biased_sort(&str_a, attr_a.ends_with("pub "), "~");
-                let mut new_str_b = str_for_biased_sort(&str_b, attr_b.ends_with("pub "), "~");
-                // move the #[macro_use] stuff above
-                // (with `!` since it's on the lower extreme of ASCII)
-                new_str_a = str_for_biased_sort(&new_str_a,
-                                                attr_a.starts_with("#[macro_use]"), "!");
-                new_str_b = str_for_biased_sort(&new_str_b,
-                                                attr_b.starts_with("#[macro_use]"), "!");
-                new_str_a.cmp(&new_str_b)
+            new_list.sort_by(|a, b| {
+                match grouped {
+                    true => use_group(&a.0).cmp(&use_group(&b.0))
+                                           .then_with(|| compare_entries(a, b, &config)),
+                    false => compare_entries(a, b, &config),
+                }
             });
 
-            for (i, (&(ref old_name, _, span_start, _warn),
-                     &(ref new_name, _, warn))) in old_list.iter()
-                                                           .zip(new_list.iter())
-                                                           .enumerate() {
+            for (i, (&(ref old_name, _, _, old_comment_len, span_start, _warn),
+                     &(ref new_name, _, _, warn))) in old_list.iter()
+                                                              .zip(new_list.iter())
+                                                              .enumerate() {
                 if (old_name != new_name) || warn {
-                    // print all the declarations proceeding the first unsorted one
-                    let suggestion_list = new_list[i..new_list.len()]
-                                          .iter()
-                                          .map(|&(ref name, ref attrs, _)| {
-                                              format!("{}{} {};", attrs, syntax, name)
-                                          }).collect::<Vec<_>>();
-
-                    // increase the span to include more lines
+                    // print all the declarations proceeding the first unsorted one, keeping each
+                    // one lined up with the indentation of the slot it's replacing and its own
+                    // attached comment glued to it as it moves; a blank line separates groups
+                    // when `grouped_imports` is in effect
+                    let mut suggestion_list = Vec::new();
+                    let mut prev_group = None;
+                    for (&(_, _, _, _, old_span, _), &(ref name, ref attrs, ref comment, _))
+                        in old_list[i..old_list.len()].iter().zip(new_list[i..new_list.len()].iter()) {
+                        if grouped {
+                            let group = use_group(name);
+                            if prev_group.map_or(false, |prev| prev != group) {
+                                suggestion_list.push(String::new());
+                            }
+                            prev_group = Some(group);
+                        }
+
+                        let indent = leading_indent(codemap, old_span);
+                        suggestion_list.push(format!("{}{}{}{} {};", comment, indent, attrs, syntax, name));
+                    }
+
+                    // increase the span to include more lines: starts at the first offending
+                    // item and ends at the last item in the group, so the whole run gets replaced
                     let mut final_span = span_start;
-                    let (_, _, old_span, _) = old_list[old_list.len() - 1];
+                    let (_, _, _, _, old_span, _) = old_list[old_list.len() - 1];
                     final_span.hi = old_span.hi;
 
+                    // `span_start` sits right at the keyword, after the line's leading
+                    // whitespace and any comment attached to whatever item originally sat here,
+                    // but the first suggestion line re-prepends both (see `indent` and
+                    // `comment` above) to carry them along with the block. Pull the start back
+                    // over that same source text too, or it'd be left behind untouched and
+                    // duplicated by the suggested copy. Rewind by the comment's actual source
+                    // byte length, not the reconstructed text's -- trailing whitespace trimmed
+                    // off each line (or a CRLF line ending) can make those two lengths diverge,
+                    // under-shooting the rewind and leaving comment bytes behind to be duplicated.
+                    let first_indent = leading_indent(codemap, span_start);
+                    let prefix_len = first_indent.len() as u32 + old_comment_len;
+                    final_span.lo = BytePos(final_span.lo.0 - prefix_len);
+
                     let message = format!("{} should be in alphabetical order!", kind);
-                    let suggestion = format!("Try this...\n\n{}\n", suggestion_list.join("\n"));
-                    cx.span_lint_help(UNSORTED_DECLARATIONS, final_span, &message, &suggestion);
+                    let suggestion = suggestion_list.join("\n");
+                    cx.struct_span_lint(UNSORTED_DECLARATIONS, final_span, &message)
+                      .span_suggestion_with_applicability(final_span, "sort them like this",
+                                                            suggestion, Applicability::MachineApplicable)
+                      .emit();
                     break
                 }
             }
         }
     }
 }
+
+impl EarlyLintPass for Sorty {
+    // The driver calls this for every module it walks, including inline ones, so we don't
+    // need to recurse into inline module bodies ourselves.
+    fn check_mod(&mut self, cx: &EarlyContext, module: &Mod, _span: Span, _id: NodeId) {
+        self.check_items(cx, &module.items);
+    }
+}